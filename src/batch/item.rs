@@ -3,6 +3,7 @@
 // (found in the LICENSE-* files in the repository)
 
 use super::PartitionKey;
+use crate::value_log::ValuePointer;
 use lsm_tree::{UserKey, UserValue, ValueType};
 
 ///
@@ -16,6 +17,14 @@ pub enum CompactItem<K, V> {
         /// Value
         value: V,
     },
+    /// Indirect value - the real value lives in the value log, this only
+    /// carries a pointer to it (see `ValueType::Indirect`)
+    ValueRef {
+        /// Key
+        key: K,
+        /// Pointer into the value log
+        ptr: ValuePointer,
+    },
     /// Tombstone
     Tombstone(K),
     /// Weak tombstone
@@ -43,9 +52,12 @@ impl<K: Eq, V> PartialEq for CompactItem<K, V> {
 impl<K: Eq, V> Eq for CompactItem<K, V> {}
 
 impl<K, V> CompactItem<K, V> {
-    fn key(&self) -> &K {
+    pub(crate) fn key(&self) -> &K {
         match self {
-            Self::Value { key, .. } | Self::Tombstone(key) | Self::WeakTombstone(key) => key,
+            Self::Value { key, .. }
+            | Self::ValueRef { key, .. }
+            | Self::Tombstone(key)
+            | Self::WeakTombstone(key) => key,
         }
     }
 }
@@ -80,6 +92,7 @@ impl std::fmt::Debug for Item {
             self.key,
             match self.value_type {
                 ValueType::Value => "V",
+                ValueType::Indirect => "I",
                 ValueType::Tombstone => "T",
                 ValueType::WeakTombstone => "W",
             },
@@ -0,0 +1,353 @@
+// Copyright (c) 2024-present, fjall-rs
+// This source code is licensed under both the Apache 2.0 and MIT License
+// (found in the LICENSE-* files in the repository)
+
+pub mod item;
+
+pub use item::{CompactItem, Item};
+
+use crate::journal::Journal;
+use lsm_tree::{UserKey, UserValue};
+use std::sync::Arc;
+
+/// Name of a partition
+pub type PartitionKey = Arc<str>;
+
+/// Anything a [`WriteBatch`] can apply committed items to and answer a
+/// fallback read from, once its own staged items have been checked.
+///
+/// Implemented by `PartitionHandle` in the full keyspace.
+pub trait PartitionWriter {
+    /// This partition's name
+    fn name(&self) -> &PartitionKey;
+
+    /// Reads the currently committed value for `key`, ignoring anything
+    /// staged in an in-flight batch
+    fn get(&self, key: &[u8]) -> crate::Result<Option<UserValue>>;
+
+    /// Applies a single item that has already been journaled.
+    ///
+    /// This must not fail for an item that was just successfully written
+    /// to the shared journal - the journal write is the atomic commit
+    /// point, so `WriteBatch::commit` applies items to each partition's
+    /// memtable one at a time afterwards, not as a second atomic step.
+    /// An `Err` here only accounts for already-fatal conditions (e.g. the
+    /// partition's own memtable is poisoned); on restart,
+    /// `crate::journal::Journal::open` replays the journal into every
+    /// partition passed to it, so every partition catches back up
+    /// regardless.
+    fn apply(&self, item: CompactItem<UserKey, UserValue>) -> crate::Result<()>;
+}
+
+/// One staged write, along with the partition it targets
+struct Staged<'a> {
+    partition: &'a dyn PartitionWriter,
+    item: CompactItem<UserKey, UserValue>,
+}
+
+/// Accumulates writes across one or more partitions and commits them
+/// atomically: either every item in the batch becomes visible, or (if the
+/// process dies mid-commit) none of them do.
+///
+/// ```no_run
+/// # use fjall::batch::WriteBatch;
+/// # fn example<'a>(mut batch: WriteBatch<'a>, a: &'a impl fjall::batch::PartitionWriter, b: &'a impl fjall::batch::PartitionWriter) -> fjall::Result<()> {
+/// batch.insert(a, "key", "value");
+/// batch.remove(b, "other-key");
+/// batch.commit()
+/// # }
+/// ```
+pub struct WriteBatch<'a> {
+    journal: &'a Journal,
+    items: Vec<Staged<'a>>,
+}
+
+impl<'a> WriteBatch<'a> {
+    /// Starts a new, empty batch committed through `journal`
+    pub fn new(journal: &'a Journal) -> Self {
+        Self {
+            journal,
+            items: Vec::new(),
+        }
+    }
+
+    /// Stages a value write in `partition`
+    ///
+    /// Panics if `key` is longer than 65535 bytes or `value` is longer
+    /// than 2^32 bytes, same as [`Item::new`].
+    pub fn insert<K: Into<UserKey>, V: Into<UserValue>>(
+        &mut self,
+        partition: &'a impl PartitionWriter,
+        key: K,
+        value: V,
+    ) {
+        let key = key.into();
+        let value = value.into();
+        assert_key_len(&key);
+        assert!(
+            u32::try_from(value.len()).is_ok(),
+            "values can be up to 2^32 bytes long"
+        );
+
+        self.items.push(Staged {
+            partition,
+            item: CompactItem::Value { key, value },
+        });
+    }
+
+    /// Stages a deletion in `partition`
+    ///
+    /// Panics if `key` is longer than 65535 bytes.
+    pub fn remove<K: Into<UserKey>>(&mut self, partition: &'a impl PartitionWriter, key: K) {
+        let key = key.into();
+        assert_key_len(&key);
+
+        self.items.push(Staged {
+            partition,
+            item: CompactItem::Tombstone(key),
+        });
+    }
+
+    /// Stages a weak deletion (see [`lsm_tree::ValueType::WeakTombstone`]) in `partition`
+    ///
+    /// Panics if `key` is longer than 65535 bytes.
+    pub fn remove_weak<K: Into<UserKey>>(&mut self, partition: &'a impl PartitionWriter, key: K) {
+        let key = key.into();
+        assert_key_len(&key);
+
+        self.items.push(Staged {
+            partition,
+            item: CompactItem::WeakTombstone(key),
+        });
+    }
+
+    /// Looks up `key` in `partition` as it would read after `commit()`:
+    /// staged items in this batch shadow the partition's committed value,
+    /// newest write wins, and a staged tombstone/weak tombstone reads as
+    /// deleted rather than falling through to the committed value. This
+    /// gives read-your-writes semantics without having to commit first,
+    /// e.g. for uniqueness checks against an earlier item in the same batch.
+    pub fn get(&self, partition: &impl PartitionWriter, key: &[u8]) -> crate::Result<Option<UserValue>> {
+        for staged in self.items.iter().rev() {
+            if staged.partition.name().as_ref() != partition.name().as_ref()
+                || staged.item.key().as_ref() != key
+            {
+                continue;
+            }
+
+            return Ok(match &staged.item {
+                CompactItem::Value { value, .. } => Some(value.clone()),
+                CompactItem::ValueRef { .. } => {
+                    return Err(crate::Error::Decode(
+                        "reading an indirect value out of an uncommitted batch is not supported",
+                    ))
+                }
+                CompactItem::Tombstone(_) | CompactItem::WeakTombstone(_) => None,
+            });
+        }
+
+        partition.get(key)
+    }
+
+    /// Sorts the batch's items by `(partition, key)` so application order
+    /// is deterministic regardless of the order they were staged in, then
+    /// writes them to the journal as a single framing record. Items are
+    /// only applied to their partitions' memtables once that record is
+    /// fully fsynced - on a journal write error, nothing is made visible.
+    ///
+    /// Durability is all-or-nothing the moment the journal write
+    /// succeeds. The loop over `PartitionWriter::apply` below is the
+    /// in-process visibility step, not a second atomic commit: if it is
+    /// interrupted (process crash, panic), the already-journaled batch is
+    /// replayed into every partition passed to `Journal::open` on the
+    /// next startup, bringing them all back in sync. See the contract on
+    /// `PartitionWriter::apply`.
+    pub fn commit(mut self) -> crate::Result<()> {
+        self.items.sort_by(|a, b| {
+            (a.partition.name().as_ref(), a.item.key()).cmp(&(b.partition.name().as_ref(), b.item.key()))
+        });
+
+        let framed: Vec<_> = self
+            .items
+            .iter()
+            .map(|staged| (staged.partition.name().clone(), clone_item(&staged.item)))
+            .collect();
+
+        self.journal.write_batch(&framed)?;
+
+        for staged in self.items {
+            staged.partition.apply(staged.item)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn assert_key_len(key: &UserKey) {
+    assert!(
+        u16::try_from(key.len()).is_ok(),
+        "keys can be up to 65535 bytes long"
+    );
+}
+
+fn clone_item(item: &CompactItem<UserKey, UserValue>) -> CompactItem<UserKey, UserValue> {
+    match item {
+        CompactItem::Value { key, value } => CompactItem::Value {
+            key: key.clone(),
+            value: value.clone(),
+        },
+        CompactItem::ValueRef { key, ptr } => CompactItem::ValueRef {
+            key: key.clone(),
+            ptr: *ptr,
+        },
+        CompactItem::Tombstone(key) => CompactItem::Tombstone(key.clone()),
+        CompactItem::WeakTombstone(key) => CompactItem::WeakTombstone(key.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, sync::Mutex};
+
+    /// A `PartitionWriter` backed by a plain map, standing in for a real
+    /// partition's memtable/committed state
+    struct FakePartition {
+        name: PartitionKey,
+        committed: Mutex<HashMap<Vec<u8>, UserValue>>,
+    }
+
+    impl FakePartition {
+        fn new(name: &str) -> Self {
+            Self {
+                name: name.into(),
+                committed: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl PartitionWriter for FakePartition {
+        fn name(&self) -> &PartitionKey {
+            &self.name
+        }
+
+        fn get(&self, key: &[u8]) -> crate::Result<Option<UserValue>> {
+            Ok(self.committed.lock().expect("lock is poisoned").get(key).cloned())
+        }
+
+        fn apply(&self, item: CompactItem<UserKey, UserValue>) -> crate::Result<()> {
+            let mut committed = self.committed.lock().expect("lock is poisoned");
+            match item {
+                CompactItem::Value { key, value } => {
+                    committed.insert(key.to_vec(), value);
+                }
+                CompactItem::Tombstone(key) | CompactItem::WeakTombstone(key) => {
+                    committed.remove(key.as_ref());
+                }
+                CompactItem::ValueRef { .. } => unreachable!("not used in this test"),
+            }
+            Ok(())
+        }
+    }
+
+    fn journal_path(tag: &str) -> std::path::PathBuf {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        std::env::temp_dir().join(format!("fjall-batch-test-{tag}-{}-{n}", std::process::id()))
+    }
+
+    #[test]
+    fn get_falls_back_to_committed_value() {
+        let path = journal_path("fallback");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+        let partition = FakePartition::new("p");
+        partition
+            .committed
+            .lock()
+            .expect("lock is poisoned")
+            .insert(b"key".to_vec(), b"committed".as_slice().into());
+
+        let batch = WriteBatch::new(&journal);
+        assert_eq!(
+            batch.get(&partition, b"key").expect("get failed"),
+            Some(b"committed".as_slice().into())
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn get_sees_own_staged_write_before_commit() {
+        let path = journal_path("staged-write");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+        let partition = FakePartition::new("p");
+
+        let mut batch = WriteBatch::new(&journal);
+        batch.insert(&partition, "key", "staged");
+
+        assert_eq!(
+            batch.get(&partition, b"key").expect("get failed"),
+            Some(b"staged".as_slice().into())
+        );
+        // Not actually committed yet
+        assert_eq!(partition.get(b"key").expect("get failed"), None);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn get_prefers_newest_staged_write_for_same_key() {
+        let path = journal_path("newest-wins");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+        let partition = FakePartition::new("p");
+
+        let mut batch = WriteBatch::new(&journal);
+        batch.insert(&partition, "key", "first");
+        batch.insert(&partition, "key", "second");
+
+        assert_eq!(
+            batch.get(&partition, b"key").expect("get failed"),
+            Some(b"second".as_slice().into())
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn get_sees_staged_tombstone_as_deleted() {
+        let path = journal_path("tombstone");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+        let partition = FakePartition::new("p");
+        partition
+            .committed
+            .lock()
+            .expect("lock is poisoned")
+            .insert(b"key".to_vec(), b"committed".as_slice().into());
+
+        let mut batch = WriteBatch::new(&journal);
+        batch.remove(&partition, "key");
+
+        assert_eq!(batch.get(&partition, b"key").expect("get failed"), None);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn commit_applies_items_and_makes_them_readable() {
+        let path = journal_path("commit");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+        let partition = FakePartition::new("p");
+
+        let mut batch = WriteBatch::new(&journal);
+        batch.insert(&partition, "key", "value");
+        batch.commit().expect("commit failed");
+
+        assert_eq!(
+            partition.get(b"key").expect("get failed"),
+            Some(b"value".as_slice().into())
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright (c) 2024-present, fjall-rs
+// This source code is licensed under both the Apache 2.0 and MIT License
+// (found in the LICENSE-* files in the repository)
+
+//! The keyspace-wide journal.
+//!
+//! Each partition keeps its own write-ahead log for single-partition
+//! durability, but a [`crate::batch::WriteBatch`] spans multiple
+//! partitions and needs a single atomic commit point shared by all of
+//! them - that's what this journal provides.
+
+use crate::batch::{item::CompactItem, PartitionKey, PartitionWriter};
+use lsm_tree::{UserKey, UserValue};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// A single item in a batch, tagged with the partition it targets
+pub type BatchItem = (PartitionKey, CompactItem<UserKey, UserValue>);
+
+/// Smallest an encoded batch item can possibly be: a 1-byte partition
+/// length, a 2-byte key length, and a 1-byte tag (partition, key, and
+/// value/pointer payload can all be zero-length in a corrupted record).
+const MIN_ENCODED_ITEM_SIZE: usize = 1 + 2 + 1;
+
+/// The shared, append-only journal that makes cross-partition batches
+/// atomic: a batch becomes durable the moment its framing record is fully
+/// written and fsynced, all at once. Its items become visible afterwards,
+/// when `WriteBatch::commit` applies them to their partitions.
+pub struct Journal {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Journal {
+    /// Opens (or creates) the journal file at `path`, replaying every
+    /// complete batch already in the journal into `partitions` first.
+    /// Batches that were already applied are replayed as well; the journal
+    /// does not record which batches have been applied.
+    ///
+    /// This is what closes the crash window in `WriteBatch::commit`:
+    /// between the journal fsync and the last `PartitionWriter::apply`
+    /// call, the process can die with some partitions already caught up
+    /// and others not. Since every item that made it into the journal is
+    /// re-applied here on the next open (tagged by partition name, so
+    /// only the partitions actually present in `partitions` are touched),
+    /// every partition ends up consistent again before the keyspace
+    /// accepts its first write. Partitions must already be open by the
+    /// time this runs - the caller performing keyspace startup is
+    /// expected to open all of its partitions before opening the shared
+    /// journal.
+    pub fn open<P: AsRef<Path>>(path: P, partitions: &[&dyn PartitionWriter]) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        for batch in Self::recover(path)? {
+            for (partition_name, item) in batch {
+                if let Some(partition) = partitions
+                    .iter()
+                    .find(|partition| partition.name().as_ref() == partition_name.as_ref())
+                {
+                    partition.apply(item)?;
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)?;
+
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Writes `items` as a single framing record, trailed by a checksum,
+    /// and fsyncs before returning. Until this returns `Ok`, none of
+    /// `items` may be applied to any partition's memtable; if it returns
+    /// `Err`, none of them may be either.
+    pub fn write_batch(&self, items: &[BatchItem]) -> crate::Result<()> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&(items.len() as u32).to_be_bytes());
+
+        for (partition, item) in items {
+            encode_batch_item(&mut record, partition, item);
+        }
+
+        let checksum = crc32(&record);
+
+        let mut writer = self.writer.lock().expect("lock is poisoned");
+        writer.write_all(&record)?;
+        writer.write_all(&checksum.to_be_bytes())?;
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+
+        Ok(())
+    }
+
+    /// Reads back every complete batch in the journal, in commit order,
+    /// without applying any of them. A
+    /// journal that doesn't exist yet (first-ever open) simply has no
+    /// batches to replay.
+    ///
+    /// A trailing record whose checksum is missing or doesn't match -
+    /// the signature of a crash mid-write - is discarded in full rather
+    /// than partially replayed.
+    pub fn recover<P: AsRef<Path>>(path: P) -> crate::Result<Vec<Vec<BatchItem>>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut file = BufReader::new(file);
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let mut batches = Vec::new();
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            let Some(record_start) = Some(pos) else { break };
+
+            let Some(count_bytes) = buf.get(pos..pos + 4) else {
+                break;
+            };
+            let count = u32::from_be_bytes(count_bytes.try_into().expect("slice is 4 bytes"));
+            pos += 4;
+
+            // `count` comes straight off disk and may be a torn/corrupted
+            // write; clamp the pre-allocation against what the remaining
+            // buffer could possibly hold instead of trusting it outright,
+            // so a bad count can't drive an unbounded allocation.
+            let max_possible_items = (buf.len() - pos) / MIN_ENCODED_ITEM_SIZE;
+            let mut items = Vec::with_capacity((count as usize).min(max_possible_items));
+            let mut truncated = false;
+
+            for _ in 0..count {
+                match decode_batch_item(&buf, &mut pos) {
+                    Some(item) => items.push(item),
+                    None => {
+                        truncated = true;
+                        break;
+                    }
+                }
+            }
+
+            if truncated {
+                break;
+            }
+
+            let Some(checksum_bytes) = buf.get(pos..pos + 4) else {
+                break;
+            };
+            let expected = u32::from_be_bytes(checksum_bytes.try_into().expect("slice is 4 bytes"));
+            let actual = crc32(&buf[record_start..pos]);
+            pos += 4;
+
+            if actual != expected {
+                break;
+            }
+
+            batches.push(items);
+        }
+
+        Ok(batches)
+    }
+}
+
+/// CRC-32 (IEEE 802.3 polynomial), computed bitwise rather than via a
+/// lookup table since this crate has no dependency on an external CRC
+/// implementation. Unlike `DefaultHasher`, this algorithm is fixed, so a
+/// journal written by one version of fjall stays verifiable by the next.
+fn crc32(bytes: &[u8]) -> u32 {
+    const POLY: u32 = 0xEDB8_8320;
+
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn encode_batch_item(buf: &mut Vec<u8>, partition: &PartitionKey, item: &CompactItem<UserKey, UserValue>) {
+    let partition = partition.as_bytes();
+    buf.push(u8::try_from(partition.len()).expect("partition name too long"));
+    buf.extend_from_slice(partition);
+
+    let key = item.key();
+    buf.extend_from_slice(
+        &u16::try_from(key.len())
+            .expect("keys can be up to 65535 bytes long")
+            .to_be_bytes(),
+    );
+    buf.extend_from_slice(key);
+
+    match item {
+        CompactItem::Value { value, .. } => {
+            buf.push(0);
+            buf.extend_from_slice(
+                &u32::try_from(value.len())
+                    .expect("values can be up to 2^32 bytes long")
+                    .to_be_bytes(),
+            );
+            buf.extend_from_slice(value);
+        }
+        CompactItem::ValueRef { ptr, .. } => {
+            buf.push(1);
+            buf.extend_from_slice(&ptr.encode());
+        }
+        CompactItem::Tombstone(_) => buf.push(2),
+        CompactItem::WeakTombstone(_) => buf.push(3),
+    }
+}
+
+fn decode_batch_item(buf: &[u8], pos: &mut usize) -> Option<BatchItem> {
+    let partition_len = *buf.get(*pos)? as usize;
+    *pos += 1;
+    let partition: PartitionKey = std::str::from_utf8(buf.get(*pos..*pos + partition_len)?)
+        .ok()?
+        .into();
+    *pos += partition_len;
+
+    let key_len = u16::from_be_bytes(buf.get(*pos..*pos + 2)?.try_into().ok()?) as usize;
+    *pos += 2;
+    let key: UserKey = buf.get(*pos..*pos + key_len)?.into();
+    *pos += key_len;
+
+    let tag = *buf.get(*pos)?;
+    *pos += 1;
+
+    let item = match tag {
+        0 => {
+            let value_len = u32::from_be_bytes(buf.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+            *pos += 4;
+            let value: UserValue = buf.get(*pos..*pos + value_len)?.into();
+            *pos += value_len;
+            CompactItem::Value { key, value }
+        }
+        1 => {
+            let ptr_bytes = buf.get(*pos..*pos + crate::value_log::ValuePointer::ENCODED_LEN)?;
+            *pos += crate::value_log::ValuePointer::ENCODED_LEN;
+            let ptr = crate::value_log::ValuePointer::decode(ptr_bytes).ok()?;
+            CompactItem::ValueRef { key, ptr }
+        }
+        2 => CompactItem::Tombstone(key),
+        3 => CompactItem::WeakTombstone(key),
+        _ => return None,
+    };
+
+    Some((partition, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    fn test_path(tag: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        std::env::temp_dir().join(format!("fjall-journal-test-{tag}-{}-{n}", std::process::id()))
+    }
+
+    fn value_item(key: &[u8], value: &[u8]) -> BatchItem {
+        (
+            "partition".into(),
+            CompactItem::Value {
+                key: key.into(),
+                value: value.into(),
+            },
+        )
+    }
+
+    /// A `PartitionWriter` backed by a plain map, standing in for a real
+    /// partition's memtable
+    struct FakePartition {
+        name: PartitionKey,
+        applied: std::sync::Mutex<std::collections::HashMap<Vec<u8>, UserValue>>,
+    }
+
+    impl FakePartition {
+        fn new(name: &str) -> Self {
+            Self {
+                name: name.into(),
+                applied: std::sync::Mutex::new(std::collections::HashMap::new()),
+            }
+        }
+    }
+
+    impl PartitionWriter for FakePartition {
+        fn name(&self) -> &PartitionKey {
+            &self.name
+        }
+
+        fn get(&self, key: &[u8]) -> crate::Result<Option<UserValue>> {
+            Ok(self.applied.lock().expect("lock is poisoned").get(key).cloned())
+        }
+
+        fn apply(&self, item: CompactItem<UserKey, UserValue>) -> crate::Result<()> {
+            if let CompactItem::Value { key, value } = item {
+                self.applied.lock().expect("lock is poisoned").insert(key.to_vec(), value);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn open_replays_journaled_but_unapplied_batch_into_partitions() {
+        let path = test_path("replay-on-open");
+
+        // Simulate the crash window in `WriteBatch::commit`: the batch
+        // made it into the journal (and was fsynced), but the process
+        // died before `PartitionWriter::apply` ran for it.
+        {
+            let journal = Journal::open(&path, &[]).expect("failed to open journal");
+            journal
+                .write_batch(&[value_item(b"key", b"value")])
+                .expect("write failed");
+        }
+
+        let partition = FakePartition::new("partition");
+        assert_eq!(partition.get(b"key").expect("get failed"), None);
+
+        let _journal = Journal::open(&path, &[&partition]).expect("failed to reopen journal");
+
+        assert_eq!(
+            partition.get(b"key").expect("get failed"),
+            Some(b"value".as_slice().into())
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn crc32_matches_known_vector() {
+        // "123456789" is the standard CRC-32/ISO-HDLC check string
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trips_multiple_batches() {
+        let path = test_path("roundtrip");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+
+        let batch_a = vec![value_item(b"a", b"1"), (PartitionKey::from("other"), CompactItem::Tombstone(b"b".as_slice().into()))];
+        let batch_b = vec![value_item(b"c", b"3")];
+
+        journal.write_batch(&batch_a).expect("write failed");
+        journal.write_batch(&batch_b).expect("write failed");
+
+        let recovered = Journal::recover(&path).expect("recover failed");
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].len(), batch_a.len());
+        assert_eq!(recovered[1].len(), batch_b.len());
+
+        match &recovered[1][0] {
+            (partition, CompactItem::Value { key, value }) => {
+                assert_eq!(partition.as_ref(), "partition");
+                assert_eq!(key.as_ref(), b"c");
+                assert_eq!(value.as_ref(), b"3");
+            }
+            _ => panic!("expected a Value item"),
+        }
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn discards_truncated_trailing_batch() {
+        let path = test_path("truncated");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+
+        let good_batch = vec![value_item(b"a", b"1")];
+        journal.write_batch(&good_batch).expect("write failed");
+
+        // Simulate a crash mid-write: a second batch whose framing record
+        // was only partially flushed before the process died.
+        {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .expect("failed to open journal for corruption");
+            file.write_all(&[0, 0, 0, 1, 5]).expect("write failed");
+        }
+
+        let recovered = Journal::recover(&path).expect("recover failed");
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].len(), good_batch.len());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn discards_batch_with_corrupted_huge_count_without_huge_allocation() {
+        let path = test_path("huge-count");
+        let journal = Journal::open(&path, &[]).expect("failed to open journal");
+
+        let good_batch = vec![value_item(b"a", b"1")];
+        journal.write_batch(&good_batch).expect("write failed");
+
+        // A torn write could leave an arbitrary `count` on disk; make sure
+        // a huge one is treated as truncated garbage, not an allocation
+        // request taken at face value.
+        {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .expect("failed to open journal for corruption");
+            file.write_all(&u32::MAX.to_be_bytes()).expect("write failed");
+            file.write_all(&[1, 2, 3]).expect("write failed");
+        }
+
+        let recovered = Journal::recover(&path).expect("recover failed");
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].len(), good_batch.len());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
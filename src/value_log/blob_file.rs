@@ -0,0 +1,197 @@
+// Copyright (c) 2024-present, fjall-rs
+// This source code is licensed under both the Apache 2.0 and MIT License
+// (found in the LICENSE-* files in the repository)
+
+use super::ValuePointer;
+use lsm_tree::UserValue;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// Identifies a blob file within a value log
+pub type BlobFileId = u64;
+
+/// An append-only file holding `(partition, key, value)` entries.
+///
+/// Each entry is framed with its partition and key so the garbage
+/// collector can check liveness against the LSM tree without needing a
+/// separate index; [`ValuePointer`]s returned by [`BlobFile::append`]
+/// point directly at the value bytes, so point reads never need to parse
+/// the frame header.
+pub struct BlobFile {
+    id: BlobFileId,
+    path: PathBuf,
+    file: Mutex<File>,
+    tail: AtomicU64,
+}
+
+impl BlobFile {
+    /// Creates a new, empty blob file
+    pub fn create_new<P: Into<PathBuf>>(id: BlobFileId, path: P) -> crate::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        Ok(Self {
+            id,
+            path,
+            file: Mutex::new(file),
+            tail: AtomicU64::new(0),
+        })
+    }
+
+    /// Opens an existing blob file
+    pub fn open<P: Into<PathBuf>>(id: BlobFileId, path: P) -> crate::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().read(true).append(true).open(&path)?;
+        let tail = file.metadata()?.len();
+
+        Ok(Self {
+            id,
+            path,
+            file: Mutex::new(file),
+            tail: AtomicU64::new(tail),
+        })
+    }
+
+    /// This blob file's ID
+    pub fn id(&self) -> BlobFileId {
+        self.id
+    }
+
+    /// Path to the blob file on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current size of the blob file in bytes
+    pub fn len(&self) -> u64 {
+        self.tail.load(Ordering::Acquire)
+    }
+
+    /// Appends a `(partition, key, value)` entry and returns a pointer to
+    /// the value within it.
+    pub fn append(&self, partition: &[u8], key: &[u8], value: &[u8]) -> crate::Result<ValuePointer> {
+        let mut frame = Vec::with_capacity(1 + partition.len() + 2 + key.len() + 4 + value.len());
+        frame.push(u8::try_from(partition.len()).expect("partition name too long"));
+        frame.extend_from_slice(partition);
+        frame.extend_from_slice(&u16::try_from(key.len()).expect("key too long").to_be_bytes());
+        frame.extend_from_slice(key);
+        frame.extend_from_slice(&u32::try_from(value.len()).expect("value too long").to_be_bytes());
+        let value_offset_in_frame = frame.len();
+        frame.extend_from_slice(value);
+
+        let mut file = self.file.lock().expect("lock is poisoned");
+        let entry_offset = self.tail.load(Ordering::Acquire);
+        file.write_all(&frame)?;
+        file.sync_data()?;
+        self.tail.store(entry_offset + frame.len() as u64, Ordering::Release);
+
+        Ok(ValuePointer {
+            blob_file_id: self.id,
+            offset: entry_offset + value_offset_in_frame as u64,
+            len: value.len() as u32,
+        })
+    }
+
+    /// Resolves a pointer previously returned by [`BlobFile::append`] to
+    /// its value
+    pub fn read(&self, ptr: &ValuePointer) -> crate::Result<UserValue> {
+        debug_assert_eq!(ptr.blob_file_id, self.id);
+
+        let mut buf = vec![0; ptr.len as usize];
+        let file = self.file.lock().expect("lock is poisoned");
+        file.read_exact_at(&mut buf, ptr.offset)?;
+        Ok(buf.into())
+    }
+
+    /// Iterates over every `(partition, key, pointer)` entry in this blob
+    /// file, in append order - used by the garbage collector to decide
+    /// which entries are still referenced by the LSM tree.
+    ///
+    /// Reads with `read_at` rather than through the shared file cursor:
+    /// the same handle is also used for `append`, which (being opened
+    /// with `.append(true)`) leaves the cursor parked at EOF, so a plain
+    /// `read_to_end` would see nothing.
+    pub fn iter(&self) -> crate::Result<BlobFileIter> {
+        let file = self.file.lock().expect("lock is poisoned");
+        let len = self.tail.load(Ordering::Acquire) as usize;
+        let mut buf = vec![0; len];
+        file.read_exact_at(&mut buf, 0)?;
+        drop(file);
+
+        Ok(BlobFileIter {
+            blob_file_id: self.id,
+            buf,
+            pos: 0,
+        })
+    }
+}
+
+/// Sequential reader over a [`BlobFile`]'s entries
+pub struct BlobFileIter {
+    blob_file_id: BlobFileId,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+/// A single entry read back out of a blob file by [`BlobFileIter`]
+pub struct BlobFileEntry {
+    /// Partition the value belongs to
+    pub partition: Vec<u8>,
+    /// Key the value belongs to
+    pub key: Vec<u8>,
+    /// Pointer identifying this entry's location (for liveness checks)
+    pub ptr: ValuePointer,
+    /// The value itself
+    pub value: UserValue,
+}
+
+impl Iterator for BlobFileIter {
+    type Item = BlobFileEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let partition_len = *self.buf.get(self.pos)? as usize;
+        self.pos += 1;
+        let partition = self.buf.get(self.pos..self.pos + partition_len)?.to_vec();
+        self.pos += partition_len;
+
+        let key_len = u16::from_be_bytes(self.buf.get(self.pos..self.pos + 2)?.try_into().ok()?) as usize;
+        self.pos += 2;
+        let key = self.buf.get(self.pos..self.pos + key_len)?.to_vec();
+        self.pos += key_len;
+
+        let value_len =
+            u32::from_be_bytes(self.buf.get(self.pos..self.pos + 4)?.try_into().ok()?) as usize;
+        self.pos += 4;
+
+        let value_offset = self.pos;
+        let value = self.buf.get(self.pos..self.pos + value_len)?.to_vec();
+        self.pos += value_len;
+
+        Some(BlobFileEntry {
+            partition,
+            key,
+            ptr: ValuePointer {
+                blob_file_id: self.blob_file_id,
+                offset: value_offset as u64,
+                len: value_len as u32,
+            },
+            value: value.into(),
+        })
+    }
+}
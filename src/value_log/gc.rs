@@ -0,0 +1,146 @@
+// Copyright (c) 2024-present, fjall-rs
+// This source code is licensed under both the Apache 2.0 and MIT License
+// (found in the LICENSE-* files in the repository)
+
+use super::{BlobFile, ValueLog, ValuePointer};
+
+/// Gives the garbage collector a way to check whether a blob file entry
+/// is still the value an LSM tree key points to, and to durably rewrite
+/// that pointer when the entry is relocated.
+///
+/// Implemented by the keyspace for the real store; kept as a trait here
+/// so the value log does not need to depend on partition internals.
+pub trait LiveIndex {
+    /// Returns `true` if `ptr` is still the pointer currently stored for
+    /// `(partition, key)`.
+    fn is_live(&self, partition: &[u8], key: &[u8], ptr: &ValuePointer) -> bool;
+
+    /// Journals and applies the relocation of `(partition, key)` from
+    /// `old` to `new`. Must be durable before returning: a crash between
+    /// the blob file rewrite and this call must not be able to leave the
+    /// LSM tree pointing at a dangling pointer.
+    fn rewrite_pointer(
+        &self,
+        partition: &[u8],
+        key: &[u8],
+        old: &ValuePointer,
+        new: &ValuePointer,
+    ) -> crate::Result<()>;
+}
+
+/// Runs one round of garbage collection: scans `blob_file` (normally the
+/// one returned by [`ValueLog::oldest_sealed`]), re-appends every entry
+/// that is still live to the head of the
+/// value log (rewriting its LSM pointer along the way), then drops the
+/// now-empty blob file.
+pub fn run_gc(value_log: &ValueLog, blob_file: &BlobFile, index: &impl LiveIndex) -> crate::Result<()> {
+    for entry in blob_file.iter()? {
+        if !index.is_live(&entry.partition, &entry.key, &entry.ptr) {
+            continue;
+        }
+
+        let new_ptr = value_log.write(&entry.partition, &entry.key, &entry.value)?;
+        index.rewrite_pointer(&entry.partition, &entry.key, &entry.ptr, &new_ptr)?;
+    }
+
+    value_log.drop_blob_file(blob_file.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_log::{ValueLog, ValueLogConfig};
+    use std::{
+        collections::HashMap,
+        sync::{atomic::AtomicU64, Mutex},
+    };
+
+    fn test_dir(tag: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        std::env::temp_dir().join(format!("fjall-gc-test-{tag}-{}-{n}", std::process::id()))
+    }
+
+    /// A `LiveIndex` backed by a plain map, standing in for the LSM tree
+    struct FakeIndex {
+        live: Mutex<HashMap<(Vec<u8>, Vec<u8>), ValuePointer>>,
+    }
+
+    impl LiveIndex for FakeIndex {
+        fn is_live(&self, partition: &[u8], key: &[u8], ptr: &ValuePointer) -> bool {
+            self.live
+                .lock()
+                .expect("lock is poisoned")
+                .get(&(partition.to_vec(), key.to_vec()))
+                == Some(ptr)
+        }
+
+        fn rewrite_pointer(
+            &self,
+            partition: &[u8],
+            key: &[u8],
+            _old: &ValuePointer,
+            new: &ValuePointer,
+        ) -> crate::Result<()> {
+            self.live
+                .lock()
+                .expect("lock is poisoned")
+                .insert((partition.to_vec(), key.to_vec()), *new);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn gc_migrates_live_entries_and_drops_dead_ones() {
+        let path = test_dir("basic");
+        let value_log = ValueLog::open(path.clone(), ValueLogConfig {
+            value_threshold: 0,
+            blob_file_target_size: u64::MAX,
+        })
+        .expect("failed to open value log");
+
+        let index = FakeIndex {
+            live: Mutex::new(HashMap::new()),
+        };
+
+        let mut ptr_live = value_log
+            .write(b"p", b"live-key", b"live-value")
+            .expect("write failed");
+        index
+            .live
+            .lock()
+            .expect("lock is poisoned")
+            .insert((b"p".to_vec(), b"live-key".to_vec()), ptr_live);
+
+        let ptr_dead = value_log
+            .write(b"p", b"dead-key", b"dead-value")
+            .expect("write failed");
+        // `dead-key` is never registered as live, simulating a key that
+        // was since overwritten or deleted.
+
+        value_log.rotate().expect("rotate failed");
+        let sealed = value_log.oldest_sealed().expect("no sealed blob file");
+        let sealed_id = sealed.id();
+
+        run_gc(&value_log, &sealed, &index).expect("gc failed");
+
+        // The dead entry should not have been migrated anywhere
+        assert!(!index.is_live(b"p", b"dead-key", &ptr_dead));
+
+        // The live entry should have been re-appended and its pointer
+        // rewritten to point at the new location
+        ptr_live = *index
+            .live
+            .lock()
+            .expect("lock is poisoned")
+            .get(&(b"p".to_vec(), b"live-key".to_vec()))
+            .expect("live key disappeared during gc");
+        assert_ne!(ptr_live.blob_file_id, sealed_id);
+        assert_eq!(
+            value_log.read(&ptr_live).expect("read failed").as_ref(),
+            b"live-value"
+        );
+
+        std::fs::remove_dir_all(&path).ok();
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright (c) 2024-present, fjall-rs
+// This source code is licensed under both the Apache 2.0 and MIT License
+// (found in the LICENSE-* files in the repository)
+
+//! Value log (WiscKey-style key-value separation).
+//!
+//! Values at or above [`ValueLogConfig::value_threshold`] are appended to a
+//! per-keyspace blob file instead of being carried around inside the LSM
+//! tree. The LSM entry then stores a small [`ValuePointer`] in place of
+//! the value (see `CompactItem::ValueRef`), so compaction only ever
+//! rewrites pointers, never the large payloads themselves.
+
+mod blob_file;
+mod gc;
+
+pub use blob_file::{BlobFile, BlobFileId};
+pub use gc::{run_gc, LiveIndex};
+
+use lsm_tree::UserValue;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+/// A pointer to a value that lives in the value log, in place of the
+/// value itself.
+///
+/// This is what a `CompactItem::ValueRef` carries, and what gets stored
+/// (encoded) as the `value` bytes of an `Item` with
+/// `value_type == ValueType::Indirect`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ValuePointer {
+    /// Which blob file the value lives in
+    pub blob_file_id: BlobFileId,
+
+    /// Byte offset of the value inside that blob file
+    pub offset: u64,
+
+    /// Length of the value in bytes
+    pub len: u32,
+}
+
+impl ValuePointer {
+    /// Encoded size in bytes
+    pub const ENCODED_LEN: usize = 8 + 8 + 4;
+
+    /// Encodes the pointer into its on-disk representation
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut buf = [0; Self::ENCODED_LEN];
+        buf[0..8].copy_from_slice(&self.blob_file_id.to_be_bytes());
+        buf[8..16].copy_from_slice(&self.offset.to_be_bytes());
+        buf[16..20].copy_from_slice(&self.len.to_be_bytes());
+        buf
+    }
+
+    /// Decodes a pointer from its on-disk representation
+    pub fn decode(bytes: &[u8]) -> crate::Result<Self> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(crate::Error::Decode("invalid value pointer length"));
+        }
+
+        let blob_file_id = u64::from_be_bytes(bytes[0..8].try_into().expect("slice is 8 bytes"));
+        let offset = u64::from_be_bytes(bytes[8..16].try_into().expect("slice is 8 bytes"));
+        let len = u32::from_be_bytes(bytes[16..20].try_into().expect("slice is 4 bytes"));
+
+        Ok(Self {
+            blob_file_id,
+            offset,
+            len,
+        })
+    }
+}
+
+/// Configuration for a [`ValueLog`]
+#[derive(Clone, Debug)]
+pub struct ValueLogConfig {
+    /// Values at or above this size (in bytes) are written to the value
+    /// log instead of being stored inline in the LSM tree
+    pub value_threshold: u32,
+
+    /// A blob file is sealed and a new one rotated in once it reaches
+    /// this size
+    pub blob_file_target_size: u64,
+}
+
+impl Default for ValueLogConfig {
+    fn default() -> Self {
+        Self {
+            value_threshold: 4_096,
+            blob_file_target_size: 64 * 1_024 * 1_024,
+        }
+    }
+}
+
+/// Manages the set of blob files backing a single keyspace's value log.
+pub struct ValueLog {
+    base_path: PathBuf,
+    config: ValueLogConfig,
+    next_blob_file_id: AtomicU64,
+    active: RwLock<Arc<BlobFile>>,
+    sealed: RwLock<BTreeMap<BlobFileId, Arc<BlobFile>>>,
+}
+
+impl ValueLog {
+    /// Opens (or creates) the value log rooted at `base_path`
+    pub fn open<P: Into<PathBuf>>(base_path: P, config: ValueLogConfig) -> crate::Result<Self> {
+        let base_path = base_path.into();
+        std::fs::create_dir_all(&base_path)?;
+
+        let mut sealed = BTreeMap::new();
+        let mut max_id = 0;
+
+        for entry in std::fs::read_dir(&base_path)? {
+            let entry = entry?;
+            let Some(id) = blob_file_id_from_path(&entry.path()) else {
+                continue;
+            };
+            max_id = max_id.max(id);
+            sealed.insert(id, Arc::new(BlobFile::open(id, entry.path())?));
+        }
+
+        let active_id = max_id + 1;
+        let active = Arc::new(BlobFile::create_new(
+            active_id,
+            blob_file_path(&base_path, active_id),
+        )?);
+
+        Ok(Self {
+            base_path,
+            config,
+            next_blob_file_id: AtomicU64::new(active_id + 1),
+            active: RwLock::new(active),
+            sealed: RwLock::new(sealed),
+        })
+    }
+
+    /// Returns `true` if a value of `len` bytes should be written
+    /// indirectly through the value log rather than stored inline.
+    pub fn should_redirect(&self, len: usize) -> bool {
+        len as u64 >= u64::from(self.config.value_threshold)
+    }
+
+    /// Appends `partition`/`key`/`value` to the active blob file, rotating
+    /// to a new one if it has reached the target size, and returns a
+    /// pointer to the written value.
+    pub fn write(&self, partition: &[u8], key: &[u8], value: &[u8]) -> crate::Result<ValuePointer> {
+        {
+            let active = self.active.read().expect("lock is poisoned");
+            if active.len() < self.config.blob_file_target_size {
+                return active.append(partition, key, value);
+            }
+        }
+        self.rotate()?;
+        self.active
+            .read()
+            .expect("lock is poisoned")
+            .append(partition, key, value)
+    }
+
+    /// Resolves a pointer to its value
+    pub fn read(&self, ptr: &ValuePointer) -> crate::Result<UserValue> {
+        if let Some(blob_file) = self.blob_file(ptr.blob_file_id) {
+            return blob_file.read(ptr);
+        }
+        Err(crate::Error::Decode("value pointer references unknown blob file"))
+    }
+
+    /// Seals the active blob file and opens a fresh one
+    pub fn rotate(&self) -> crate::Result<()> {
+        let id = self.next_blob_file_id.fetch_add(1, Ordering::SeqCst);
+        let new_active = Arc::new(BlobFile::create_new(id, blob_file_path(&self.base_path, id))?);
+
+        let old_active = {
+            let mut active = self.active.write().expect("lock is poisoned");
+            std::mem::replace(&mut *active, new_active)
+        };
+
+        self.sealed
+            .write()
+            .expect("lock is poisoned")
+            .insert(old_active.id(), old_active);
+
+        Ok(())
+    }
+
+    /// The oldest sealed blob file, if any - the natural candidate for GC
+    pub fn oldest_sealed(&self) -> Option<Arc<BlobFile>> {
+        self.sealed
+            .read()
+            .expect("lock is poisoned")
+            .values()
+            .next()
+            .cloned()
+    }
+
+    /// Removes a blob file from the sealed set and deletes it on disk.
+    ///
+    /// Should only be called once the garbage collector has re-appended
+    /// every live entry elsewhere.
+    pub fn drop_blob_file(&self, id: BlobFileId) -> crate::Result<()> {
+        let blob_file = self.sealed.write().expect("lock is poisoned").remove(&id);
+        if let Some(blob_file) = blob_file {
+            std::fs::remove_file(blob_file.path())?;
+        }
+        Ok(())
+    }
+
+    fn blob_file(&self, id: BlobFileId) -> Option<Arc<BlobFile>> {
+        if self.active.read().expect("lock is poisoned").id() == id {
+            return Some(self.active.read().expect("lock is poisoned").clone());
+        }
+        self.sealed.read().expect("lock is poisoned").get(&id).cloned()
+    }
+}
+
+fn blob_file_path(base_path: &Path, id: BlobFileId) -> PathBuf {
+    base_path.join(id.to_string())
+}
+
+fn blob_file_id_from_path(path: &Path) -> Option<BlobFileId> {
+    path.file_name()?.to_str()?.parse().ok()
+}